@@ -3,6 +3,13 @@ use crate::H256;
 #[derive(Debug, Clone)]
 pub enum Error {
     MissingKey(H256),
+    /// a supplied proof was malformed bytes or hex, did not contain enough sibling hashes to
+    /// rebuild the root, or was given no leaves to rebuild from
+    InvalidProof,
+    /// tried to prove absence of a key that is actually set
+    KeyExists(H256),
+    /// a [`crate::Store`] backend failed; carries its description
+    Store(String),
 }
 
 pub type Result<T> = ::std::result::Result<T, Error>;
@@ -1,33 +1,25 @@
 use crate::H256;
-use std::collections::hash_map::DefaultHasher;
-use std::hash::Hasher;
-use std::mem::transmute;
 
-/// merge two hashes
-pub fn merge(lhs: &H256, rhs: &H256) -> H256 {
-    let mut hash = [0u8; 32];
-    let mut counter: usize = 0;
-    let mut hasher = DefaultHasher::new();
-    hasher.write(lhs);
-    hasher.write(rhs);
-    let hash64: [u8; 8] = unsafe { transmute(hasher.finish().to_be()) };
-    hash64.iter().for_each(|val| {
-        hash[counter] = *val;
-        counter += 1
-    });
-    let hash_value = |value| -> [u8; 8] {
-        let mut hasher = DefaultHasher::new();
-        hasher.write(value);
-        unsafe { transmute(hasher.finish().to_be()) }
-    };
-    loop {
-        let hash64 = hash_value(&hash64);
-        for val in &hash64 {
-            hash[counter] = *val;
-            counter += 1;
-        }
-        if counter == hash.len() {
-            return hash;
-        }
+/// cryptographic hash function used to merge two sibling nodes into their parent.
+pub trait Hasher {
+    /// hash of an empty leaf; an empty subtree hashes to this merged with itself once per level
+    const ZERO: H256;
+
+    /// merge two child hashes into their parent hash
+    fn merge(&self, lhs: &H256, rhs: &H256) -> H256;
+}
+
+/// Default [`Hasher`] backed by BLAKE3.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Blake3Hasher;
+
+impl Hasher for Blake3Hasher {
+    const ZERO: H256 = [0u8; 32];
+
+    fn merge(&self, lhs: &H256, rhs: &H256) -> H256 {
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(lhs);
+        hasher.update(rhs);
+        *hasher.finalize().as_bytes()
     }
 }
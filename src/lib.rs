@@ -1,19 +1,46 @@
 mod error;
 mod hash;
+mod proof;
+mod store;
 
 use error::{Error, Result};
-use hash::merge;
+pub use hash::{Blake3Hasher, Hasher};
+pub use proof::{compute_root, verify_absence, verify_proof, MerkleProof};
+pub use store::{FileStore, MemoryStore, Store, TreeCache};
 use std::borrow::Cow;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 pub type H256 = [u8; 32];
-pub type TreeCache = HashMap<H256, (H256, H256)>;
 /// leaves default hash
 pub const ZERO_HASH: H256 = [0u8; 32];
-const TREE_HEIGHT: usize = std::mem::size_of::<H256>() * 8;
-const HIGHEST_BIT_POS: u8 = 7;
+pub(crate) const TREE_HEIGHT: usize = std::mem::size_of::<H256>() * 8;
+pub(crate) const HIGHEST_BIT_POS: u8 = 7;
 
-#[derive(Debug, PartialEq)]
+/// bit at `idx` (0 = most significant bit) of `key`, read in the same MSB-first order as
+/// [`PathIter`].
+pub(crate) fn bit_at(key: &H256, idx: usize) -> u8 {
+    let byte = key[idx / 8];
+    (byte >> (HIGHEST_BIT_POS as usize - idx % 8)) & 1
+}
+
+/// whether `a` and `b` agree on their first `bits` bits (MSB-first).
+pub(crate) fn shares_prefix(a: &H256, b: &H256, bits: usize) -> bool {
+    (0..bits).all(|i| bit_at(a, i) == bit_at(b, i))
+}
+
+/// lowercase hex encoding, used for [`MerkleProof`] round-tripping and [`FileStore`]'s node
+/// filenames.
+pub(crate) fn hex_encode(bytes: &[u8]) -> String {
+    const DIGITS: &[u8; 16] = b"0123456789abcdef";
+    let mut hex = String::with_capacity(bytes.len() * 2);
+    for byte in bytes {
+        hex.push(DIGITS[(byte >> 4) as usize] as char);
+        hex.push(DIGITS[(byte & 0x0f) as usize] as char);
+    }
+    hex
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Branch {
     Left = 0,
     Right = 1,
@@ -21,7 +48,7 @@ pub enum Branch {
 
 /// H256 path iterator
 /// iterate from left to right, from higher bit to lower bit.
-struct PathIter<'a> {
+pub(crate) struct PathIter<'a> {
     path: &'a H256,
     bit_pos: u8,
     byte_pos: u8,
@@ -59,114 +86,334 @@ impl<'a> Iterator for PathIter<'a> {
     }
 }
 
-/// Sparse merkle tree
+/// Sparse merkle tree, generic over the [`Hasher`] used to merge siblings and the [`Store`]
+/// backing its nodes.
 #[derive(Clone, Debug)]
-pub struct SparseMerkleTree {
-    pub cache: TreeCache,
-    pub root: H256,
+pub struct SparseMerkleTree<H = Blake3Hasher, S = MemoryStore> {
+    store: S,
+    root: H256,
+    hasher: H,
+    /// the 256 shared default-subtree roots produced by [`Self::compute_default_tree`];
+    /// these are never reference counted nor evicted.
+    defaults: HashSet<H256>,
+    /// number of live references to each non-default node hash reachable from `root`; see
+    /// [`Self::new`], which recomputes this from `store` rather than assuming this process
+    /// built the tree itself.
+    refs: HashMap<H256, usize>,
 }
 
-impl SparseMerkleTree {
-    /// create merkle tree from root and cache
-    pub fn new(root: H256, cache: TreeCache) -> SparseMerkleTree {
-        SparseMerkleTree { root, cache }
+impl<H: Hasher, S: Store> SparseMerkleTree<H, S> {
+    /// wrap an existing root and store, reconstructing `refs` by walking every node reachable
+    /// from `root`: a freshly wrapped store may already hold nodes from a previous process, and
+    /// without this they'd start at refcount 0 and could be evicted while still referenced.
+    pub fn new(root: H256, store: S, hasher: H) -> Result<SparseMerkleTree<H, S>> {
+        let defaults = Self::default_hashes(&hasher);
+        let mut tree = SparseMerkleTree {
+            root,
+            store,
+            hasher,
+            defaults,
+            refs: HashMap::new(),
+        };
+        tree.refs = tree.compute_refs()?;
+        Ok(tree)
     }
 
-    pub fn compute_default_tree() -> SparseMerkleTree {
-        let mut hash = ZERO_HASH;
-        let mut cache: TreeCache = Default::default();
+    pub fn compute_default_tree(hasher: H, mut store: S) -> Result<SparseMerkleTree<H, S>> {
+        let mut hash = H::ZERO;
+        let mut defaults = HashSet::with_capacity(256);
         for _ in 0..256 {
-            let parent = merge(&hash, &hash);
-            cache.insert(parent, (hash, hash));
+            let parent = hasher.merge(&hash, &hash);
+            store.insert_node(parent, hash, hash)?;
+            defaults.insert(parent);
             hash = parent;
         }
-        SparseMerkleTree::new(hash, cache)
+        store.set_root(hash)?;
+        Ok(SparseMerkleTree {
+            root: hash,
+            store,
+            hasher,
+            defaults,
+            refs: HashMap::new(),
+        })
+    }
+
+    /// recompute the 256 shared default-subtree roots for `hasher`, without touching the store.
+    fn default_hashes(hasher: &H) -> HashSet<H256> {
+        let mut hash = H::ZERO;
+        let mut defaults = HashSet::with_capacity(256);
+        for _ in 0..256 {
+            hash = hasher.merge(&hash, &hash);
+            defaults.insert(hash);
+        }
+        defaults
+    }
+
+    /// count, for every non-default node reachable from `root`, how many reachable nodes point
+    /// to it as a child — i.e. what `refs` would hold had this process inserted every node
+    /// itself.
+    fn compute_refs(&self) -> Result<HashMap<H256, usize>> {
+        let mut refs = HashMap::new();
+        let mut expanded = HashSet::new();
+        let mut stack = vec![self.root];
+        expanded.insert(self.root);
+        while let Some(hash) = stack.pop() {
+            if self.defaults.contains(&hash) {
+                continue;
+            }
+            if let Some((left, right)) = self.store.get_node(&hash)? {
+                for child in [left, right] {
+                    if self.defaults.contains(&child) {
+                        continue;
+                    }
+                    *refs.entry(child).or_insert(0) += 1;
+                    if expanded.insert(child) {
+                        stack.push(child);
+                    }
+                }
+            }
+        }
+        Ok(refs)
+    }
+
+    /// the tree's current root.
+    pub fn root(&self) -> H256 {
+        self.root
+    }
+
+    /// the tree's backing store.
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    /// look up an interior node's children, or [`Error::MissingKey`] if it isn't in the store.
+    fn node(&self, hash: &H256) -> Result<(H256, H256)> {
+        self.store.get_node(hash)?.ok_or(Error::MissingKey(*hash))
+    }
+
+    /// register a freshly merged node and account for the references it makes to its children.
+    /// a no-op if `hash` is already present: since the store is content-addressed, the same
+    /// hash always carries the same children, so no new edges are created.
+    fn insert_node(&mut self, hash: H256, left: H256, right: H256) -> Result<()> {
+        if self.defaults.contains(&hash) || self.store.get_node(&hash)?.is_some() {
+            return Ok(());
+        }
+        self.store.insert_node(hash, left, right)?;
+        self.bump_ref(left);
+        self.bump_ref(right);
+        Ok(())
     }
 
-    /// add or update leaf value.
+    /// record one more live reference to `hash`.
+    fn bump_ref(&mut self, hash: H256) {
+        if self.defaults.contains(&hash) {
+            return;
+        }
+        *self.refs.entry(hash).or_insert(0) += 1;
+    }
+
+    /// drop one reference to `hash`; once it reaches zero, evict the node and recursively
+    /// release the references it was holding on its own children.
+    fn release(&mut self, hash: H256) -> Result<()> {
+        if self.defaults.contains(&hash) {
+            return Ok(());
+        }
+        if let Some(count) = self.refs.get_mut(&hash) {
+            *count -= 1;
+            if *count == 0 {
+                self.refs.remove(&hash);
+                if let Some((left, right)) = self.store.remove_node(&hash)? {
+                    self.release(left)?;
+                    self.release(right)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// drop the tree's own pointer to a root that is about to be replaced. unlike interior
+    /// nodes, a root has no incoming reference to decrement, so it is evicted outright (once
+    /// it is no longer the root) and its children are released as usual.
+    fn forget_root(&mut self, hash: H256) -> Result<()> {
+        if self.defaults.contains(&hash) {
+            return Ok(());
+        }
+        if let Some((left, right)) = self.store.remove_node(&hash)? {
+            self.refs.remove(&hash);
+            self.release(left)?;
+            self.release(right)?;
+        }
+        Ok(())
+    }
+
+    /// add or update leaf value. Setting `value` to [`Hasher::ZERO`] deletes the key: see
+    /// [`Self::delete`].
     pub fn insert(&mut self, key: &H256, value: H256) -> Result<()> {
-        let mut node = &self.root;
+        let mut node = self.root;
         let mut siblings = Vec::with_capacity(256);
         for branch in PathIter::from(key) {
-            let parent = self.cache.get(node).ok_or(Error::MissingKey(*node))?;
+            let parent = self.node(&node)?;
             match branch {
                 Branch::Left => {
                     siblings.push(parent.1);
-                    node = &parent.0;
+                    node = parent.0;
                 }
                 Branch::Right => {
                     siblings.push(parent.0);
-                    node = &parent.1;
+                    node = parent.1;
                 }
             }
         }
+        let old_root = self.root;
         let mut node = value;
         for branch in PathIter::from(key).collect::<Vec<_>>().into_iter().rev() {
             let sibling = siblings.pop().expect("sibling should exsits");
-            match branch {
+            let new_parent = match branch {
                 Branch::Left => {
-                    let new_parent = merge(&node, &sibling);
-                    self.cache.insert(new_parent, (node, sibling));
-                    node = new_parent;
+                    let parent = self.hasher.merge(&node, &sibling);
+                    self.insert_node(parent, node, sibling)?;
+                    parent
                 }
                 Branch::Right => {
-                    let new_parent = merge(&sibling, &node);
-                    self.cache.insert(new_parent, (sibling, node));
-                    node = new_parent;
+                    let parent = self.hasher.merge(&sibling, &node);
+                    self.insert_node(parent, sibling, node)?;
+                    parent
                 }
-            }
+            };
+            node = new_parent;
         }
         self.root = node;
+        if old_root != self.root {
+            self.store.set_root(self.root)?;
+            self.forget_root(old_root)?;
+        }
         Ok(())
     }
 
+    /// remove a key from the tree, pruning any interior node left unreferenced by the update.
+    ///
+    /// following the convention of updating a key to the default value, this is equivalent to
+    /// `insert(key, H::ZERO)`.
+    pub fn delete(&mut self, key: &H256) -> Result<()> {
+        self.insert(key, H::ZERO)
+    }
+
     /// get leaf value. If value isn`t null, also return it`s merkle path.
-    pub fn get<'a>(&self, key: &'a H256) -> Result<(&H256, Option<Vec<Branch>>)> {
-        let mut node = &self.root;
+    pub fn get(&self, key: &H256) -> Result<(H256, Option<Vec<Branch>>)> {
+        let mut node = self.root;
         let mut path = vec![];
         for branch in PathIter::from(key) {
-            let parent = self.cache.get(node).ok_or(Error::MissingKey(*node))?;
+            let parent = self.node(&node)?;
             match branch {
-                Branch::Left => node = &parent.0,
-                Branch::Right => node = &parent.1,
+                Branch::Left => node = parent.0,
+                Branch::Right => node = parent.1,
             }
             path.push(branch);
         }
-        if *node != ZERO_HASH {
+        if node != H::ZERO {
             Ok((node, Some(path)))
         } else {
             Ok((node, None))
         }
     }
 
-    /// generate merkle proof
-    fn merkle_proof(&self, path: &[Branch]) -> Result<Vec<H256>> {
-        let mut node = &self.root;
+    /// generate a single-key merkle proof, self-contained and independent of the tree: see
+    /// [`MerkleProof`] and [`verify_proof`].
+    pub fn merkle_proof(&self, key: &H256) -> Result<MerkleProof> {
+        let path: Vec<Branch> = PathIter::from(key).collect();
+        let siblings = self.proof_siblings(&path)?;
+        Ok(MerkleProof { path, siblings })
+    }
+
+    /// prove that `key` is *absent*: every unset position in a sparse tree deterministically
+    /// resolves to its default subtree, so this is just the inclusion proof of the empty leaf
+    /// along `key`'s path. Errors with [`Error::KeyExists`] if `key` is actually set.
+    ///
+    /// verify with [`verify_absence`].
+    pub fn prove_absence(&self, key: &H256) -> Result<MerkleProof> {
+        let (value, _) = self.get(key)?;
+        if value != H::ZERO {
+            return Err(Error::KeyExists(*key));
+        }
+        self.merkle_proof(key)
+    }
+
+    /// ordered sibling hashes along `path`, from the root down to the leaf.
+    fn proof_siblings(&self, path: &[Branch]) -> Result<Vec<H256>> {
+        let mut node = self.root;
         let mut proof = Vec::with_capacity(256);
         for branch in path {
-            let parent = self.cache.get(node).ok_or(Error::MissingKey(*node))?;
+            let parent = self.node(&node)?;
             match branch {
                 Branch::Left => {
                     proof.push(parent.1);
-                    node = &parent.0;
+                    node = parent.0;
                 }
                 Branch::Right => {
                     proof.push(parent.0);
-                    node = &parent.1;
+                    node = parent.1;
                 }
             }
         }
         Ok(proof)
     }
 
+    /// generate a compact batch proof for `keys`, for use with the standalone
+    /// [`compute_root`]. Unlike a [`MerkleProof`] per key, the result only contains the sibling
+    /// hashes a verifier cannot derive from the proven leaves themselves: when two proven keys
+    /// share a parent at some level, that pair contributes no proof entry at that level, since
+    /// the parent is simply the merge of the two leaves' own (recursively proven) hashes.
+    ///
+    /// keys are deduplicated and the returned proof is ordered bottom-up (bit 255 first, bit 0
+    /// last), matching the order [`compute_root`] consumes it in.
+    pub fn merkle_proof_multi(&self, keys: &[H256]) -> Result<Vec<H256>> {
+        let mut keys = keys.to_vec();
+        keys.sort();
+        keys.dedup();
+
+        let mut list = Vec::with_capacity(keys.len());
+        let mut siblings_by_key = HashMap::with_capacity(keys.len());
+        for key in &keys {
+            let path: Vec<Branch> = PathIter::from(key).collect();
+            siblings_by_key.insert(*key, self.proof_siblings(&path)?);
+            list.push((*key, self.get(key)?.0));
+        }
+
+        let mut proof = Vec::new();
+        for level in (0..TREE_HEIGHT).rev() {
+            let mut next = Vec::with_capacity(list.len());
+            let mut i = 0;
+            while i < list.len() {
+                if i + 1 < list.len() && shares_prefix(&list[i].0, &list[i + 1].0, level) {
+                    let hash = self.hasher.merge(&list[i].1, &list[i + 1].1);
+                    next.push((list[i].0, hash));
+                    i += 2;
+                } else {
+                    let (key, value) = list[i];
+                    let sibling = siblings_by_key[&key][level];
+                    proof.push(sibling);
+                    let hash = if bit_at(&key, level) == 0 {
+                        self.hasher.merge(&value, &sibling)
+                    } else {
+                        self.hasher.merge(&sibling, &value)
+                    };
+                    next.push((key, hash));
+                    i += 1;
+                }
+            }
+            list = next;
+        }
+        Ok(proof)
+    }
+
     /// verify merkle path provided.
     pub fn verify(&self, value: &H256, path: &[Branch]) -> Result<bool> {
-        let proof = self.merkle_proof(path)?;
+        let proof = self.proof_siblings(path)?;
         if proof.len() != TREE_HEIGHT {
             return Ok(false);
         }
         let mut node = Cow::Borrowed(value);
-        for (i, branch) in path.into_iter().rev().enumerate() {
+        for (i, branch) in path.iter().rev().enumerate() {
             let sibling = match proof.get(TREE_HEIGHT - i - 1) {
                 Some(sibling) => sibling,
                 None => {
@@ -175,10 +422,10 @@ impl SparseMerkleTree {
             };
             match branch {
                 Branch::Left => {
-                    node = Cow::Owned(merge(node.as_ref(), sibling));
+                    node = Cow::Owned(self.hasher.merge(node.as_ref(), sibling));
                 }
                 Branch::Right => {
-                    node = Cow::Owned(merge(sibling, node.as_ref()));
+                    node = Cow::Owned(self.hasher.merge(sibling, node.as_ref()));
                 }
             }
         }
@@ -189,22 +436,25 @@ impl SparseMerkleTree {
 #[cfg(test)]
 mod tests {
     use super::*;
+
     #[test]
     fn test_default_root() {
-        let tree = SparseMerkleTree::compute_default_tree();
-        assert_eq!(tree.cache.len(), 256);
+        let tree = SparseMerkleTree::compute_default_tree(Blake3Hasher, MemoryStore::default())
+            .expect("compute_default_tree");
+        assert_eq!(tree.store().len(), 256);
         assert_eq!(
-            tree.root,
+            tree.root(),
             [
-                140, 164, 124, 238, 105, 175, 51, 44, 10, 239, 182, 210, 7, 199, 111, 54, 10, 239,
-                182, 210, 7, 199, 111, 54, 10, 239, 182, 210, 7, 199, 111, 54
+                114, 12, 0, 172, 195, 107, 129, 120, 56, 205, 133, 168, 132, 168, 124, 186, 225,
+                204, 31, 99, 184, 5, 233, 215, 221, 221, 173, 225, 169, 29, 252, 203
             ]
         );
     }
 
     #[test]
     fn test_insert() {
-        let mut tree = SparseMerkleTree::compute_default_tree();
+        let mut tree = SparseMerkleTree::compute_default_tree(Blake3Hasher, MemoryStore::default())
+            .expect("compute_default_tree");
         let key = [
             242, 160, 143, 147, 201, 240, 57, 245, 126, 181, 190, 235, 95, 42, 240, 169, 94, 190,
             197, 240, 67, 46, 153, 190, 244, 230, 180, 164, 230, 230, 230, 240,
@@ -214,15 +464,43 @@ mod tests {
             120, 236, 205, 174, 144, 138, 191, 158, 229, 217, 64, 152, 245,
         ];
         let (val1, path1) = tree.get(&key).expect("get");
-        assert_eq!((val1, path1), (&ZERO_HASH, None));
+        assert_eq!((val1, path1), (ZERO_HASH, None));
         tree.insert(&key, value).expect("insert");
         let (val2, path2) = tree.get(&key).expect("get");
-        assert!(val2 == &value && path2.is_some());
+        assert!(val2 == value && path2.is_some());
+    }
+
+    #[test]
+    fn test_delete() {
+        let mut tree = SparseMerkleTree::compute_default_tree(Blake3Hasher, MemoryStore::default())
+            .expect("compute_default_tree");
+        let key = [
+            57, 12, 140, 125, 114, 71, 52, 44, 216, 16, 15, 47, 111, 119, 13, 101, 214, 112, 229,
+            142, 3, 81, 216, 174, 142, 79, 110, 172, 52, 47, 194, 49,
+        ];
+        let value = [
+            183, 176, 135, 22, 235, 63, 193, 40, 150, 185, 98, 35, 23, 116, 148, 40, 119, 51, 194,
+            142, 232, 186, 83, 189, 181, 107, 136, 36, 87, 125, 83, 236,
+        ];
+        let default_root = tree.root();
+        let default_store_len = tree.store().len();
+        tree.insert(&key, value).expect("insert");
+        assert!(tree.store().len() > default_store_len);
+        tree.delete(&key).expect("delete");
+        let (val, path) = tree.get(&key).expect("get");
+        assert_eq!((val, path), (ZERO_HASH, None));
+        assert_eq!(tree.root(), default_root);
+        assert_eq!(
+            tree.store().len(),
+            default_store_len,
+            "deleting the only key should prune every node it introduced"
+        );
     }
 
     #[test]
     fn test_verify() {
-        let mut tree = SparseMerkleTree::compute_default_tree();
+        let mut tree = SparseMerkleTree::compute_default_tree(Blake3Hasher, MemoryStore::default())
+            .expect("compute_default_tree");
         let key = [
             77, 160, 178, 147, 201, 240, 57, 245, 126, 181, 190, 235, 95, 42, 240, 169, 94, 190,
             197, 240, 67, 46, 153, 190, 244, 230, 180, 164, 230, 230, 66, 240,
@@ -236,10 +514,84 @@ mod tests {
         assert!(tree.verify(&value, &path.expect("path")).expect("verify"));
     }
 
+    #[test]
+    fn test_merkle_proof_multi() {
+        let mut tree = SparseMerkleTree::compute_default_tree(Blake3Hasher, MemoryStore::default())
+            .expect("compute_default_tree");
+        let entries: Vec<(H256, H256)> = (0u8..8)
+            .map(|i| {
+                let mut key = ZERO_HASH;
+                key[0] = i;
+                let mut value = ZERO_HASH;
+                value[31] = i + 1;
+                (key, value)
+            })
+            .collect();
+        for (key, value) in &entries {
+            tree.insert(key, *value).expect("insert");
+        }
+
+        let keys: Vec<H256> = entries.iter().map(|(key, _)| *key).collect();
+        let proof = tree.merkle_proof_multi(&keys).expect("merkle_proof_multi");
+        let root = compute_root(&Blake3Hasher, entries, &proof).expect("compute_root");
+        assert_eq!(root, tree.root());
+    }
+
+    #[test]
+    fn test_merkle_proof_roundtrip() {
+        let mut tree = SparseMerkleTree::compute_default_tree(Blake3Hasher, MemoryStore::default())
+            .expect("compute_default_tree");
+        let key = [
+            194, 138, 112, 166, 28, 117, 16, 161, 205, 137, 33, 108, 161, 108, 255, 202, 234, 73,
+            135, 71, 126, 134, 219, 204, 185, 112, 70, 252, 46, 24, 56, 78,
+        ];
+        let value = [
+            81, 216, 32, 197, 195, 239, 128, 5, 58, 136, 174, 57, 150, 222, 80, 232, 1, 134, 91,
+            54, 152, 101, 78, 191, 82, 0, 165, 250, 9, 57, 185, 157,
+        ];
+        tree.insert(&key, value).expect("insert");
+
+        let proof = tree.merkle_proof(&key).expect("merkle_proof");
+        let bytes = proof.to_bytes();
+        let decoded = MerkleProof::from_bytes(&bytes).expect("from_bytes");
+        assert_eq!(proof, decoded);
+        assert_eq!(MerkleProof::from_hex(&proof.to_hex()).expect("from_hex"), proof);
+
+        assert!(verify_proof(&Blake3Hasher, &tree.root(), &key, &value, &proof));
+        assert!(!verify_proof(&Blake3Hasher, &tree.root(), &key, &ZERO_HASH, &proof));
+    }
+
+    #[test]
+    fn test_prove_absence() {
+        let mut tree = SparseMerkleTree::compute_default_tree(Blake3Hasher, MemoryStore::default())
+            .expect("compute_default_tree");
+        let absent_key = [
+            122, 29, 123, 40, 43, 248, 35, 64, 65, 243, 84, 135, 216, 108, 102, 159, 204, 191,
+            224, 231, 61, 126, 115, 32, 173, 10, 117, 112, 3, 36, 30, 117,
+        ];
+        let other_key = [
+            34, 16, 169, 36, 121, 142, 248, 109, 67, 242, 124, 242, 208, 97, 48, 49, 220, 181,
+            216, 210, 239, 27, 50, 31, 206, 173, 55, 127, 98, 97, 229, 71,
+        ];
+        let other_value = [
+            216, 93, 142, 236, 127, 38, 226, 50, 25, 7, 47, 121, 85, 208, 248, 246, 109, 205, 30,
+            84, 194, 1, 199, 135, 232, 146, 216, 249, 79, 97, 151, 111,
+        ];
+        tree.insert(&other_key, other_value).expect("insert");
+
+        let proof = tree.prove_absence(&absent_key).expect("prove_absence");
+        assert!(verify_absence(&Blake3Hasher, &tree.root(), &absent_key, &proof));
+        assert!(matches!(
+            tree.prove_absence(&other_key),
+            Err(Error::KeyExists(key)) if key == other_key
+        ));
+    }
+
     #[test]
     #[should_panic]
     fn test_verify_should_panic() {
-        let tree = SparseMerkleTree::compute_default_tree();
+        let tree = SparseMerkleTree::compute_default_tree(Blake3Hasher, MemoryStore::default())
+            .expect("compute_default_tree");
         let value = [
             77, 160, 178, 147, 201, 240, 57, 245, 126, 181, 190, 235, 95, 42, 240, 169, 94, 190,
             197, 240, 67, 46, 153, 190, 244, 230, 180, 164, 230, 230, 66, 240,
@@ -247,4 +599,62 @@ mod tests {
         let path = vec![Branch::Left, Branch::Right, Branch::Left];
         assert!(tree.verify(&value, &path).expect("verify"));
     }
+
+    #[test]
+    fn test_file_store() {
+        let dir = std::env::temp_dir().join(format!("sparse-smt-test-{:?}", std::thread::current().id()));
+        let store = FileStore::open(&dir).expect("open");
+        let mut tree = SparseMerkleTree::compute_default_tree(Blake3Hasher, store)
+            .expect("compute_default_tree");
+        // keys first diverge at bit 0, so they sit in opposite halves and key_a's subtree hangs
+        // directly off the root: this is what exposed refs desyncing on reload (see compute_refs).
+        let key_a = [
+            1, 3, 5, 7, 9, 11, 13, 15, 17, 19, 21, 23, 25, 27, 29, 31, 33, 35, 37, 39, 41, 43, 45,
+            47, 49, 51, 53, 55, 57, 59, 61, 63,
+        ];
+        let value_a = [
+            2, 4, 6, 8, 10, 12, 14, 16, 18, 20, 22, 24, 26, 28, 30, 32, 34, 36, 38, 40, 42, 44,
+            46, 48, 50, 52, 54, 56, 58, 60, 62, 64,
+        ];
+        let key_b = [
+            129, 131, 133, 135, 137, 139, 141, 143, 145, 147, 149, 151, 153, 155, 157, 159, 161,
+            163, 165, 167, 169, 171, 173, 175, 177, 179, 181, 183, 185, 187, 189, 191,
+        ];
+        let value_b = [
+            200, 201, 202, 203, 204, 205, 206, 207, 208, 209, 210, 211, 212, 213, 214, 215, 216,
+            217, 218, 219, 220, 221, 222, 223, 224, 225, 226, 227, 228, 229, 230, 231,
+        ];
+        tree.insert(&key_a, value_a).expect("insert a");
+        tree.insert(&key_b, value_b).expect("insert b");
+        drop(tree);
+
+        let reopened = FileStore::open(&dir).expect("reopen");
+        let root = reopened.get_root().expect("get_root");
+        let mut reloaded = SparseMerkleTree::new(root, reopened, Blake3Hasher).expect("new");
+
+        // mutate after reload: without reconstructing refs, this evicts key_a's still-live nodes.
+        let value_b2 = [
+            10, 20, 30, 40, 50, 60, 70, 80, 90, 100, 110, 120, 130, 140, 150, 160, 170, 180, 190,
+            200, 210, 220, 230, 240, 250, 4, 14, 24, 34, 44, 54, 64,
+        ];
+        reloaded.insert(&key_b, value_b2).expect("update b");
+
+        let (val_a, path_a) = reloaded.get(&key_a).expect("get a");
+        assert_eq!(val_a, value_a);
+        assert!(reloaded
+            .verify(&val_a, &path_a.expect("path a"))
+            .expect("verify a"));
+
+        reloaded.delete(&key_b).expect("delete b");
+        let (val_b, path_b) = reloaded.get(&key_b).expect("get b");
+        assert_eq!((val_b, path_b), (ZERO_HASH, None));
+
+        let (val_a, path_a) = reloaded.get(&key_a).expect("get a again");
+        assert_eq!(val_a, value_a);
+        assert!(reloaded
+            .verify(&val_a, &path_a.expect("path a"))
+            .expect("verify a"));
+
+        std::fs::remove_dir_all(&dir).expect("cleanup");
+    }
 }
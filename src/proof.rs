@@ -0,0 +1,151 @@
+use crate::hash::Hasher;
+use crate::{
+    bit_at, hex_encode, shares_prefix, Branch, Error, PathIter, Result, H256, HIGHEST_BIT_POS,
+    TREE_HEIGHT, ZERO_HASH,
+};
+
+/// rebuild a merkle root from proven `(key, value)` leaves and the compact sibling list
+/// produced by [`crate::SparseMerkleTree::merkle_proof_multi`].
+pub fn compute_root<H: Hasher>(
+    hasher: &H,
+    mut leaves: Vec<(H256, H256)>,
+    proof: &[H256],
+) -> Result<H256> {
+    leaves.sort_by_key(|leaf| leaf.0);
+    leaves.dedup_by(|a, b| a.0 == b.0);
+
+    let mut list = leaves;
+    let mut proof = proof.iter();
+    for level in (0..TREE_HEIGHT).rev() {
+        let mut next = Vec::with_capacity(list.len());
+        let mut i = 0;
+        while i < list.len() {
+            if i + 1 < list.len() && shares_prefix(&list[i].0, &list[i + 1].0, level) {
+                let hash = hasher.merge(&list[i].1, &list[i + 1].1);
+                next.push((list[i].0, hash));
+                i += 2;
+            } else {
+                let sibling = *proof.next().ok_or(Error::InvalidProof)?;
+                let (key, value) = list[i];
+                let hash = if bit_at(&key, level) == 0 {
+                    hasher.merge(&value, &sibling)
+                } else {
+                    hasher.merge(&sibling, &value)
+                };
+                next.push((key, hash));
+                i += 1;
+            }
+        }
+        list = next;
+    }
+
+    match list.as_slice() {
+        [(_, root)] => Ok(*root),
+        _ => Err(Error::InvalidProof),
+    }
+}
+
+/// self-contained, single-key merkle proof: see [`verify_proof`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MerkleProof {
+    pub path: Vec<Branch>,
+    pub siblings: Vec<H256>,
+}
+
+impl MerkleProof {
+    /// pack the proof into `32 + 32 * siblings.len()` bytes: the path encoded as a 32-byte
+    /// bitmap (`Branch::Right` bits set, in the same MSB-first order as the key it was derived
+    /// from), followed by the sibling hashes top-down.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(32 + self.siblings.len() * 32);
+        bytes.extend_from_slice(&pack_path(&self.path));
+        for sibling in &self.siblings {
+            bytes.extend_from_slice(sibling);
+        }
+        bytes
+    }
+
+    /// inverse of [`Self::to_bytes`].
+    pub fn from_bytes(bytes: &[u8]) -> Result<MerkleProof> {
+        if bytes.len() < 32 || !(bytes.len() - 32).is_multiple_of(32) {
+            return Err(Error::InvalidProof);
+        }
+        let mut packed_path = ZERO_HASH;
+        packed_path.copy_from_slice(&bytes[..32]);
+        let path = PathIter::from(&packed_path).collect();
+        let siblings = bytes[32..]
+            .chunks_exact(32)
+            .map(|chunk| {
+                let mut sibling = ZERO_HASH;
+                sibling.copy_from_slice(chunk);
+                sibling
+            })
+            .collect();
+        Ok(MerkleProof { path, siblings })
+    }
+
+    pub fn to_hex(&self) -> String {
+        hex_encode(&self.to_bytes())
+    }
+
+    pub fn from_hex(hex: &str) -> Result<MerkleProof> {
+        Self::from_bytes(&hex_decode(hex)?)
+    }
+}
+
+/// verify a [`MerkleProof`] against `root`, without needing the tree that produced it.
+pub fn verify_proof<H: Hasher>(
+    hasher: &H,
+    root: &H256,
+    key: &H256,
+    value: &H256,
+    proof: &MerkleProof,
+) -> bool {
+    if proof.path.len() != TREE_HEIGHT || proof.siblings.len() != TREE_HEIGHT {
+        return false;
+    }
+    if !PathIter::from(key).eq(proof.path.iter().copied()) {
+        return false;
+    }
+    let mut node = *value;
+    for (branch, sibling) in proof.path.iter().zip(proof.siblings.iter()).rev() {
+        node = match branch {
+            Branch::Left => hasher.merge(&node, sibling),
+            Branch::Right => hasher.merge(sibling, &node),
+        };
+    }
+    &node == root
+}
+
+/// verify that `key` is absent from the tree rooted at `root`, via [`crate::SparseMerkleTree::prove_absence`]'s proof.
+pub fn verify_absence<H: Hasher>(hasher: &H, root: &H256, key: &H256, proof: &MerkleProof) -> bool {
+    verify_proof(hasher, root, key, &H::ZERO, proof)
+}
+
+fn pack_path(path: &[Branch]) -> H256 {
+    let mut bytes = ZERO_HASH;
+    for (i, branch) in path.iter().enumerate().take(TREE_HEIGHT) {
+        if *branch == Branch::Right {
+            bytes[i / 8] |= 1 << (HIGHEST_BIT_POS as usize - i % 8);
+        }
+    }
+    bytes
+}
+
+fn hex_decode(hex: &str) -> Result<Vec<u8>> {
+    let hex = hex.as_bytes();
+    if !hex.len().is_multiple_of(2) {
+        return Err(Error::InvalidProof);
+    }
+    let nibble = |c: u8| -> Result<u8> {
+        match c {
+            b'0'..=b'9' => Ok(c - b'0'),
+            b'a'..=b'f' => Ok(c - b'a' + 10),
+            b'A'..=b'F' => Ok(c - b'A' + 10),
+            _ => Err(Error::InvalidProof),
+        }
+    };
+    hex.chunks_exact(2)
+        .map(|pair| Ok(nibble(pair[0])? << 4 | nibble(pair[1])?))
+        .collect()
+}
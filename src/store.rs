@@ -0,0 +1,143 @@
+use crate::{hex_encode, Error, Result, H256, ZERO_HASH};
+use std::collections::HashMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+
+/// backing storage for a [`crate::SparseMerkleTree`]'s nodes and root.
+///
+/// this is the extension point for persisting a tree beyond process memory: implement it
+/// against your own key-value store (RocksDB, sled, ...) and hand an instance to
+/// `SparseMerkleTree::new`/`compute_default_tree` in place of [`MemoryStore`].
+pub trait Store {
+    /// look up an interior node's two children by its hash.
+    fn get_node(&self, hash: &H256) -> Result<Option<(H256, H256)>>;
+    /// persist an interior node's two children under its hash.
+    fn insert_node(&mut self, hash: H256, left: H256, right: H256) -> Result<()>;
+    /// remove a node, returning its children if it was present.
+    fn remove_node(&mut self, hash: &H256) -> Result<Option<(H256, H256)>>;
+    /// the tree's current root.
+    fn get_root(&self) -> Result<H256>;
+    /// persist a new root.
+    fn set_root(&mut self, root: H256) -> Result<()>;
+}
+
+pub type TreeCache = HashMap<H256, (H256, H256)>;
+
+/// in-memory [`Store`] backed by a `HashMap`; the default for [`crate::SparseMerkleTree`].
+#[derive(Clone, Debug, Default)]
+pub struct MemoryStore {
+    nodes: TreeCache,
+    root: H256,
+}
+
+impl MemoryStore {
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+}
+
+impl Store for MemoryStore {
+    fn get_node(&self, hash: &H256) -> Result<Option<(H256, H256)>> {
+        Ok(self.nodes.get(hash).copied())
+    }
+
+    fn insert_node(&mut self, hash: H256, left: H256, right: H256) -> Result<()> {
+        self.nodes.insert(hash, (left, right));
+        Ok(())
+    }
+
+    fn remove_node(&mut self, hash: &H256) -> Result<Option<(H256, H256)>> {
+        Ok(self.nodes.remove(hash))
+    }
+
+    fn get_root(&self) -> Result<H256> {
+        Ok(self.root)
+    }
+
+    fn set_root(&mut self, root: H256) -> Result<()> {
+        self.root = root;
+        Ok(())
+    }
+}
+
+/// disk-backed [`Store`]: every node is a 64-byte `left || right` file named by its own hash,
+/// with the root in a dedicated `ROOT` file.
+#[derive(Clone, Debug)]
+pub struct FileStore {
+    dir: PathBuf,
+}
+
+impl FileStore {
+    /// open (and create, if missing) a directory-backed store.
+    pub fn open(dir: impl Into<PathBuf>) -> Result<FileStore> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir).map_err(store_err)?;
+        Ok(FileStore { dir })
+    }
+
+    fn node_path(&self, hash: &H256) -> PathBuf {
+        self.dir.join(hex_encode(hash))
+    }
+
+    fn root_path(&self) -> PathBuf {
+        self.dir.join("ROOT")
+    }
+}
+
+impl Store for FileStore {
+    fn get_node(&self, hash: &H256) -> Result<Option<(H256, H256)>> {
+        match fs::read(self.node_path(hash)) {
+            Ok(bytes) if bytes.len() == 64 => {
+                let mut left = ZERO_HASH;
+                let mut right = ZERO_HASH;
+                left.copy_from_slice(&bytes[..32]);
+                right.copy_from_slice(&bytes[32..]);
+                Ok(Some((left, right)))
+            }
+            Ok(_) => Err(Error::Store("corrupt node file".to_string())),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(store_err(err)),
+        }
+    }
+
+    fn insert_node(&mut self, hash: H256, left: H256, right: H256) -> Result<()> {
+        let mut bytes = Vec::with_capacity(64);
+        bytes.extend_from_slice(&left);
+        bytes.extend_from_slice(&right);
+        fs::write(self.node_path(&hash), bytes).map_err(store_err)
+    }
+
+    fn remove_node(&mut self, hash: &H256) -> Result<Option<(H256, H256)>> {
+        let existing = self.get_node(hash)?;
+        if existing.is_some() {
+            fs::remove_file(self.node_path(hash)).map_err(store_err)?;
+        }
+        Ok(existing)
+    }
+
+    fn get_root(&self) -> Result<H256> {
+        match fs::read(self.root_path()) {
+            Ok(bytes) if bytes.len() == 32 => {
+                let mut root = ZERO_HASH;
+                root.copy_from_slice(&bytes);
+                Ok(root)
+            }
+            Ok(_) => Err(Error::Store("corrupt root file".to_string())),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(ZERO_HASH),
+            Err(err) => Err(store_err(err)),
+        }
+    }
+
+    fn set_root(&mut self, root: H256) -> Result<()> {
+        fs::write(self.root_path(), root).map_err(store_err)
+    }
+}
+
+fn store_err(err: std::io::Error) -> Error {
+    Error::Store(err.to_string())
+}